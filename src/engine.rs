@@ -15,39 +15,447 @@
  * ------------------------------------------------------------------------------
  */
 
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fmt::{self, Write};
+use std::fs;
+use std::hash::Hash;
+use std::mem;
 use std::str::FromStr;
 use std::sync::mpsc::{Receiver, RecvTimeoutError};
-use std::thread::sleep;
 use std::time;
 
 use rand;
 use rand::Rng;
 
 use sawtooth_sdk::consensus::{engine::*, service::Service};
+use sawtooth_sdk::signing::{
+    create_context,
+    secp256k1::{Secp256k1PrivateKey, Secp256k1PublicKey},
+    Context, PrivateKey, PublicKey,
+};
 
 const DEFAULT_WAIT_TIME: u64 = 0;
 const NULL_BLOCK_IDENTIFIER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
 
+// Tag prefixed to the consensus bytes in both plain and authority mode, kept
+// for backwards compatibility with chains that predate authority signing.
+const CONSENSUS_TAG: &[u8] = b"Devmode";
+const SETTING_AUTHORITIES: &str = "sawtooth.consensus.authorities";
+// Where the local authority signing key is read from when devmode is run in
+// permissioned mode. Overridable for tests and non-default deployments.
+//
+// This is deliberately a key of its own, not the validator's own network
+// identity key (conventionally /etc/sawtooth/keys/validator.priv): that
+// key already has a role -- signing the validator's own registration and
+// transactions -- and reusing it here would let a signature produced for
+// one role be replayed as the other. SEAL_SIGNING_CONTEXT guards against
+// that even if an operator points both roles at the same key anyway.
+const DEFAULT_AUTHORITY_KEY_PATH: &str = "/etc/sawtooth/keys/devmode_authority.priv";
+const AUTHORITY_KEY_PATH_ENV: &str = "SAWTOOTH_CONSENSUS_AUTHORITY_KEY";
+
+const TIMESTAMP_LEN: usize = 8; // big-endian consensus timestamp, seconds since epoch
+const PUBLIC_KEY_LEN: usize = 33; // compressed secp256k1 public key
+const SIGNATURE_LEN: usize = 64; // compact secp256k1 signature
+// Marks a seal as carrying the timestamp field added after authority mode
+// shipped. Its length alone (legacy seals are a fixed, shorter length)
+// would disambiguate the two formats, but an explicit tag makes that
+// deliberate rather than incidental the next time the format changes.
+const SEAL_VERSION_TIMESTAMPED: u8 = 1;
+// The height below which a legacy (no-timestamp) seal is still accepted,
+// for chains that ran authority mode before the timestamp field existed.
+// Defaults to 0 -- i.e. every block requires the current seal format --
+// so accepting the old format is something an operator opts into for a
+// specific upgrade window, not a standing way to skip timestamp-signing.
+const SETTING_SEAL_MIN_TIMESTAMPED_HEIGHT: &str = "sawtooth.consensus.seal_min_timestamped_height";
+
+const SETTING_FORK_CHOICE: &str = "sawtooth.consensus.fork_choice";
+const FORK_CHOICE_TIMESTAMP: &str = "timestamp";
+
+const PREVOTE_MESSAGE: &str = "prevote";
+const PRECOMMIT_MESSAGE: &str = "precommit";
+const SETTING_VALIDATOR_SET_SIZE: &str = "sawtooth.consensus.validator_set_size";
+// How long a node waits to reach precommit quorum on a candidate before
+// giving up on it and falling back to whatever the next BlockValid brings.
+const VOTING_ROUND_TIMEOUT: time::Duration = time::Duration::from_secs(8);
+
+// How long to wait before retrying a summarize/finalize call that returned
+// BlockNotReady.
+const PUBLISH_RETRY_DELAY: time::Duration = time::Duration::from_secs(1);
+
+// Caps how many distinct not-yet-synced block_ids can have votes buffered
+// in pending_prevotes/pending_precommits at once, so a peer gossiping
+// votes for block_ids this node never syncs can't grow those maps without
+// bound.
+const MAX_PENDING_VOTE_BLOCKS: usize = 64;
+
+// A map from pending-action key to the instant its retry is due, modeled on
+// the delay-queue used for scheduled retries in network layers like
+// Lighthouse's `HashMapDelay`. Checking `poll_expired` on every iteration of
+// the main loop, instead of sleeping until the deadline, keeps `recv_timeout`
+// running so `Update`s (including `Shutdown`) are never stalled behind a
+// pending retry.
+struct HashMapDelay<K> {
+    deadlines: HashMap<K, time::Instant>,
+}
+
+impl<K: Eq + Hash + Clone> HashMapDelay<K> {
+    fn new() -> Self {
+        HashMapDelay {
+            deadlines: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, key: K, delay: time::Duration) {
+        self.deadlines.insert(key, time::Instant::now() + delay);
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.deadlines.remove(key);
+    }
+
+    fn poll_expired(&mut self) -> Vec<K> {
+        let now = time::Instant::now();
+        let expired: Vec<K> = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &expired {
+            self.deadlines.remove(key);
+        }
+
+        expired
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash)]
+enum PendingAction {
+    PublishRetry,
+}
+
+// Where the summarize/finalize pipeline for the block currently being
+// published is up to.
+enum PublishState {
+    Idle,
+    AwaitingFinalize(Vec<u8>),
+}
+
 #[derive(Default)]
 struct LogGuard {
     not_ready_to_summarize: bool,
     not_ready_to_finalize: bool,
 }
 
+/// Local signing identity used to seal blocks when devmode is configured to
+/// run as a permissioned authority-set chain rather than plain round-robin.
+struct AuthorityConfig {
+    context: Box<dyn Context>,
+    signer: Box<dyn PrivateKey>,
+    public_key: Box<dyn PublicKey>,
+}
+
+/// The consensus seal embedded in a block's consensus bytes in authority
+/// mode: who signed it, their signature, and -- for seals written after
+/// the `Timestamp` fork-choice rule was added -- when the block was
+/// produced. `timestamp` is `None` for seals in the original, shorter
+/// authority-mode format so a chain that turned authority mode on before
+/// this field existed doesn't fail consensus on its own history the
+/// moment this format changes; `verify_seal` knows to check those against
+/// just the summary, the way they were originally signed.
+/// `check_consensus` only accepts a `None` timestamp below the configured
+/// `seal_min_timestamped_height`, so the old format can't be used to skip
+/// timestamp-signing on a block produced today.
+struct Seal {
+    timestamp: Option<u64>,
+    signer_public_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl Seal {
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(CONSENSUS_TAG);
+        if let Some(timestamp) = self.timestamp {
+            bytes.push(SEAL_VERSION_TIMESTAMPED);
+            bytes.extend_from_slice(&timestamp.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.signer_public_key);
+        bytes.extend_from_slice(&self.signature);
+        bytes
+    }
+
+    fn parse(consensus: &[u8]) -> Option<Seal> {
+        let tag_end = CONSENSUS_TAG.len();
+        if consensus.len() < tag_end || &consensus[..tag_end] != CONSENSUS_TAG {
+            return None;
+        }
+        let rest = &consensus[tag_end..];
+
+        let legacy_len = PUBLIC_KEY_LEN + SIGNATURE_LEN;
+        let timestamped_len = 1 + TIMESTAMP_LEN + PUBLIC_KEY_LEN + SIGNATURE_LEN;
+
+        if rest.len() == timestamped_len && rest[0] == SEAL_VERSION_TIMESTAMPED {
+            let key_start = 1 + TIMESTAMP_LEN;
+            let key_end = key_start + PUBLIC_KEY_LEN;
+
+            let mut timestamp_bytes = [0u8; TIMESTAMP_LEN];
+            timestamp_bytes.copy_from_slice(&rest[1..key_start]);
+
+            return Some(Seal {
+                timestamp: Some(u64::from_be_bytes(timestamp_bytes)),
+                signer_public_key: rest[key_start..key_end].to_vec(),
+                signature: rest[key_end..].to_vec(),
+            });
+        }
+
+        if rest.len() == legacy_len {
+            return Some(Seal {
+                timestamp: None,
+                signer_public_key: rest[..PUBLIC_KEY_LEN].to_vec(),
+                signature: rest[PUBLIC_KEY_LEN..].to_vec(),
+            });
+        }
+
+        None
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .expect("System time before UNIX epoch")
+        .as_secs()
+}
+
+fn block_timestamp(block: &Block) -> Option<u64> {
+    Seal::parse(&block.payload).and_then(|seal| seal.timestamp)
+}
+
+// Prefixed onto everything signed by the authority key, so a signature
+// produced for devmode's seal can't be replayed as a signature over some
+// other message format that happens to share the key (see the comment on
+// DEFAULT_AUTHORITY_KEY_PATH for why that's a real possibility here).
+const SEAL_SIGNING_CONTEXT: &[u8] = b"DevmodeAuthoritySeal";
+
+// The bytes actually covered by a seal's signature: a fixed domain tag,
+// then the timestamp alongside the summary, so a signer can't change the
+// timestamp without invalidating the signature (e.g. to bias
+// `TimestampForkChoice` with a forged timestamp).
+fn signed_bytes(timestamp: u64, summary: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::from(SEAL_SIGNING_CONTEXT);
+    bytes.extend_from_slice(&timestamp.to_be_bytes());
+    bytes.extend_from_slice(summary);
+    bytes
+}
+
+/// Outcome of comparing a candidate block against the current chain head.
+enum Decision {
+    Commit,
+    Fork,
+    Ignore,
+}
+
+// Walks previous_id pointers back from `block` until reaching `target_height`.
+fn walk_back_to_height(service: &mut DevmodeService, block: Block, target_height: u64) -> Block {
+    let mut chain_block = block;
+    loop {
+        chain_block = service.get_block(&chain_block.previous_id);
+        if chain_block.block_num == target_height {
+            return chain_block;
+        }
+    }
+}
+
+/// A swappable policy for deciding whether a `BlockValid` candidate should
+/// replace the current chain head, mirroring how other consensus engines
+/// make fork choice a configurable policy rather than a fixed comparison.
+trait ForkChoice {
+    fn choose(
+        &self,
+        current_head: &Block,
+        candidate: &Block,
+        service: &mut DevmodeService,
+    ) -> Decision;
+}
+
+/// The original devmode rule: advance on greater height, or equal height
+/// with a lexicographically larger block_id.
+struct GreatestIdForkChoice;
+
+impl ForkChoice for GreatestIdForkChoice {
+    fn choose(
+        &self,
+        current_head: &Block,
+        candidate: &Block,
+        service: &mut DevmodeService,
+    ) -> Decision {
+        if candidate.block_num > current_head.block_num
+            || (candidate.block_num == current_head.block_num
+                && candidate.block_id > current_head.block_id)
+        {
+            return Decision::Commit;
+        }
+
+        if candidate.block_num == current_head.block_num {
+            return Decision::Ignore;
+        }
+
+        let chain_block = walk_back_to_height(service, current_head.clone(), candidate.block_num);
+
+        if candidate.block_id > chain_block.block_id {
+            Decision::Fork
+        } else {
+            Decision::Ignore
+        }
+    }
+}
+
+/// Prefers the block with the earlier consensus timestamp, for more
+/// reproducible multi-node tests; falls back to `GreatestIdForkChoice`'s
+/// block_id tie-break when either block has no seal timestamp (e.g.
+/// devmode isn't running in authority mode) or the timestamps are equal.
+struct TimestampForkChoice;
+
+impl TimestampForkChoice {
+    fn prefers(&self, candidate: &Block, other: &Block) -> bool {
+        match (block_timestamp(candidate), block_timestamp(other)) {
+            (Some(candidate_ts), Some(other_ts)) if candidate_ts != other_ts => {
+                candidate_ts < other_ts
+            }
+            _ => candidate.block_id > other.block_id,
+        }
+    }
+}
+
+impl ForkChoice for TimestampForkChoice {
+    fn choose(
+        &self,
+        current_head: &Block,
+        candidate: &Block,
+        service: &mut DevmodeService,
+    ) -> Decision {
+        if candidate.block_num > current_head.block_num {
+            return Decision::Commit;
+        }
+
+        if candidate.block_num == current_head.block_num {
+            return if self.prefers(candidate, current_head) {
+                Decision::Commit
+            } else {
+                Decision::Ignore
+            };
+        }
+
+        let chain_block = walk_back_to_height(service, current_head.clone(), candidate.block_num);
+
+        if self.prefers(candidate, &chain_block) {
+            Decision::Fork
+        } else {
+            Decision::Ignore
+        }
+    }
+}
+
+// Authority mode is opt-in: if no local signing key is configured, devmode
+// falls back to the plain "Devmode" || summary consensus bytes.
+//
+// The key is loaded from a file rather than threaded in through
+// StartupState/Service: neither carries private key material, only chain
+// and peer info, so there's no consensus-API channel to deliver a signing
+// key through short of adding one to the SDK itself. See the comment on
+// DEFAULT_AUTHORITY_KEY_PATH for why this reads a dedicated key file.
+fn load_authority_config() -> Option<AuthorityConfig> {
+    let key_path =
+        env::var(AUTHORITY_KEY_PATH_ENV).unwrap_or_else(|_| DEFAULT_AUTHORITY_KEY_PATH.to_string());
+
+    let key_hex = fs::read_to_string(&key_path).ok()?;
+    let context = create_context("secp256k1").ok()?;
+    let private_key = Secp256k1PrivateKey::from_hex(key_hex.trim()).ok()?;
+    let public_key = context.get_public_key(private_key.as_ref()).ok()?;
+
+    Some(AuthorityConfig {
+        context,
+        signer: Box::new(private_key),
+        public_key,
+    })
+}
+
+// The block currently being prevoted/precommitted on. Only one round is
+// ever in flight, which is what keeps a node from precommitting two
+// different blocks at the same height.
+struct VotingRound {
+    block_id: BlockId,
+    block_num: u64,
+    started_at: time::Instant,
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut buf = String::new();
+    for b in bytes {
+        write!(&mut buf, "{:02x}", b).expect("Unable to write to string");
+    }
+    buf
+}
+
+fn decode_hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).expect("Invalid hex in consensus seal"))
+        .collect()
+}
+
 pub struct DevmodeService {
     service: Box<dyn Service>,
     log_guard: LogGuard,
+    authority: Option<AuthorityConfig>,
+    local_peer_id: PeerId,
+    peers: HashSet<PeerId>,
+    prevotes: HashMap<u64, HashMap<BlockId, HashSet<PeerId>>>,
+    precommits: HashMap<u64, HashMap<BlockId, HashSet<PeerId>>>,
+    // Votes for a block_id this node hasn't synced yet, so their height
+    // isn't known and they can't be recorded into prevotes/precommits.
+    // Replayed once that block_id becomes the active voting round (see
+    // start_voting_round) so a vote isn't lost just because it arrived
+    // ahead of this node's own view of the block; cleared wholesale
+    // whenever a round finalizes so it can't grow without bound.
+    pending_prevotes: HashMap<BlockId, HashSet<PeerId>>,
+    pending_precommits: HashMap<BlockId, HashSet<PeerId>>,
+    voting_round: Option<VotingRound>,
+    highest_committed_height: u64,
+    publish_state: PublishState,
+    retry_queue: HashMapDelay<PendingAction>,
 }
 
 impl DevmodeService {
     pub fn new(service: Box<dyn Service>) -> Self {
+        let authority = load_authority_config();
+        if authority.is_some() {
+            info!("Authority signing key loaded; running devmode in authority mode");
+        }
+
         DevmodeService {
             service,
             log_guard: LogGuard::default(),
+            authority,
+            local_peer_id: PeerId::new(),
+            peers: HashSet::new(),
+            prevotes: HashMap::new(),
+            precommits: HashMap::new(),
+            pending_prevotes: HashMap::new(),
+            pending_precommits: HashMap::new(),
+            voting_round: None,
+            highest_committed_height: 0,
+            publish_state: PublishState::Idle,
+            retry_queue: HashMapDelay::new(),
         }
     }
 
+    pub fn set_local_peer_id(&mut self, peer_id: PeerId) {
+        self.local_peer_id = peer_id;
+    }
+
     fn get_chain_head(&mut self) -> Block {
         debug!("Getting chain head");
         self.service
@@ -65,6 +473,34 @@ impl DevmodeService {
             .unwrap()
     }
 
+    // Like `get_block`, but for blocks we only have a peer's word for (e.g.
+    // the block_id carried by a gossiped prevote/precommit): returns None
+    // instead of panicking when the block isn't known locally yet, since a
+    // vote can legitimately arrive before this node has synced the block
+    // it's for.
+    #[allow(clippy::ptr_arg)]
+    fn try_get_block(&mut self, block_id: &BlockId) -> Option<Block> {
+        self.service
+            .get_blocks(vec![block_id.clone()])
+            .ok()
+            .and_then(|mut blocks| blocks.remove(block_id))
+    }
+
+    // The height of a gossiped vote's block_id, resolved without a service
+    // round-trip when it's the block this node is already running a
+    // voting round for -- by far the common case, since every prevote and
+    // precommit gossiped for a round is about the same block_id.
+    #[allow(clippy::ptr_arg)]
+    fn vote_block_num(&mut self, block_id: &BlockId) -> Option<u64> {
+        let active_round_num = self
+            .voting_round
+            .as_ref()
+            .filter(|round| &round.block_id == block_id)
+            .map(|round| round.block_num);
+
+        active_round_num.or_else(|| self.try_get_block(block_id).map(|block| block.block_num))
+    }
+
     fn initialize_block(&mut self) {
         debug!("Initializing block");
         self.service
@@ -72,39 +508,74 @@ impl DevmodeService {
             .expect("Failed to initialize");
     }
 
-    fn finalize_block(&mut self) -> BlockId {
-        debug!("Finalizing block");
-        let mut summary = self.service.summarize_block();
-        while let Err(Error::BlockNotReady) = summary {
-            if !self.log_guard.not_ready_to_summarize {
-                self.log_guard.not_ready_to_summarize = true;
-                debug!("Block not ready to summarize");
-            }
-            sleep(time::Duration::from_secs(1));
-            summary = self.service.summarize_block();
-        }
-        self.log_guard.not_ready_to_summarize = false;
-        let summary = summary.expect("Failed to summarize block");
-        debug!("Block has been summarized successfully");
-
-        let consensus: Vec<u8> = create_consensus(&summary);
-        let mut block_id = self.service.finalize_block(consensus.clone());
-        while let Err(Error::BlockNotReady) = block_id {
-            if !self.log_guard.not_ready_to_finalize {
-                self.log_guard.not_ready_to_finalize = true;
-                debug!("Block not ready to finalize");
+    // Drives the summarize/finalize pipeline one step. Returns the new
+    // block_id once finalize succeeds; if either step isn't ready yet, it
+    // schedules a retry on `retry_queue` and returns None instead of
+    // blocking, so the caller's main loop keeps servicing other `Update`s.
+    fn try_publish(&mut self) -> Option<BlockId> {
+        loop {
+            let state = mem::replace(&mut self.publish_state, PublishState::Idle);
+
+            match state {
+                PublishState::Idle => {
+                    debug!("Summarizing block");
+                    match self.service.summarize_block() {
+                        Ok(summary) => {
+                            self.log_guard.not_ready_to_summarize = false;
+                            debug!("Block has been summarized successfully");
+                            let consensus = self.create_consensus(&summary);
+                            self.publish_state = PublishState::AwaitingFinalize(consensus);
+                        }
+                        Err(Error::BlockNotReady) => {
+                            if !self.log_guard.not_ready_to_summarize {
+                                self.log_guard.not_ready_to_summarize = true;
+                                debug!("Block not ready to summarize");
+                            }
+                            self.retry_queue
+                                .insert(PendingAction::PublishRetry, PUBLISH_RETRY_DELAY);
+                            return None;
+                        }
+                        Err(err) => panic!("Failed to summarize block: {:?}", err),
+                    }
+                }
+
+                PublishState::AwaitingFinalize(consensus) => {
+                    debug!("Finalizing block");
+                    match self.service.finalize_block(consensus.clone()) {
+                        Ok(block_id) => {
+                            self.log_guard.not_ready_to_finalize = false;
+                            debug!(
+                                "Block has been finalized successfully: {}",
+                                to_hex(&block_id)
+                            );
+                            return Some(block_id);
+                        }
+                        Err(Error::BlockNotReady) => {
+                            if !self.log_guard.not_ready_to_finalize {
+                                self.log_guard.not_ready_to_finalize = true;
+                                debug!("Block not ready to finalize");
+                            }
+                            self.publish_state = PublishState::AwaitingFinalize(consensus);
+                            self.retry_queue
+                                .insert(PendingAction::PublishRetry, PUBLISH_RETRY_DELAY);
+                            return None;
+                        }
+                        Err(err) => panic!("Failed to finalize block: {:?}", err),
+                    }
+                }
             }
-            sleep(time::Duration::from_secs(1));
-            block_id = self.service.finalize_block(consensus.clone());
-        }
-        self.log_guard.not_ready_to_finalize = false;
-        let block_id = block_id.expect("Failed to finalize block");
-        debug!(
-            "Block has been finalized successfully: {}",
-            to_hex(&block_id)
-        );
+        }
+    }
 
-        block_id
+    // Drops any in-progress publish attempt, e.g. because the block being
+    // summarized/finalized was abandoned for a new chain head.
+    fn reset_publish_state(&mut self) {
+        self.publish_state = PublishState::Idle;
+        self.retry_queue.remove(&PendingAction::PublishRetry);
+    }
+
+    fn poll_expired_retries(&mut self) -> Vec<PendingAction> {
+        self.retry_queue.poll_expired()
     }
 
     fn check_block(&mut self, block_id: BlockId) {
@@ -210,6 +681,462 @@ impl DevmodeService {
 
         time::Duration::from_secs(wait_time)
     }
+
+    // Produce the consensus bytes to finalize a block with. In authority
+    // mode this is a seal over the summary signed with the local authority
+    // key; otherwise it's the plain tag used by round-robin devmode.
+    fn create_consensus(&mut self, summary: &[u8]) -> Vec<u8> {
+        match &self.authority {
+            Some(authority) => {
+                let timestamp = unix_timestamp();
+                let signature_hex = authority
+                    .context
+                    .sign(&signed_bytes(timestamp, summary), authority.signer.as_ref())
+                    .expect("Failed to sign block summary");
+
+                let seal = Seal {
+                    timestamp: Some(timestamp),
+                    signer_public_key: authority.public_key.as_slice().to_vec(),
+                    signature: decode_hex(&signature_hex),
+                };
+
+                seal.as_bytes()
+            }
+            None => {
+                let mut consensus: Vec<u8> = Vec::from(CONSENSUS_TAG);
+                consensus.extend_from_slice(summary);
+                consensus
+            }
+        }
+    }
+
+    // Verify a received block's consensus bytes. In authority mode this
+    // parses the seal, checks the signer is an authorized key as of the
+    // block's parent, and verifies the signature over the summary; otherwise
+    // it just checks the plain consensus bytes match.
+    fn check_consensus(&mut self, block: &Block) -> bool {
+        if self.authority.is_none() {
+            return block.payload == self.create_consensus(&block.summary);
+        }
+
+        let seal = match Seal::parse(&block.payload) {
+            Some(seal) => seal,
+            None => return false,
+        };
+
+        // A legacy (no-timestamp) seal is only valid below the configured
+        // cutover height -- otherwise any signer could dodge the
+        // timestamp commitment TimestampForkChoice relies on just by
+        // shipping a seal in the older, shorter shape.
+        if seal.timestamp.is_none() {
+            let min_timestamped_height =
+                self.seal_min_timestamped_height(block.previous_id.clone());
+            if block.block_num >= min_timestamped_height {
+                return false;
+            }
+        }
+
+        let authorities = self.get_authorities(block.previous_id.clone());
+        if !authorities.contains(&encode_hex(&seal.signer_public_key)) {
+            return false;
+        }
+
+        self.verify_seal(&seal, &block.summary)
+    }
+
+    // Height below which a legacy, untimestamped seal is still accepted,
+    // read from sawtooth.consensus.seal_min_timestamped_height. Unset (or
+    // unparseable) means 0: every block must use the current seal format.
+    fn seal_min_timestamped_height(&mut self, settings_block_id: BlockId) -> u64 {
+        self.service
+            .get_settings(
+                settings_block_id,
+                vec![String::from(SETTING_SEAL_MIN_TIMESTAMPED_HEIGHT)],
+            )
+            .ok()
+            .and_then(|settings| settings.get(SETTING_SEAL_MIN_TIMESTAMPED_HEIGHT).cloned())
+            .and_then(|raw| raw.trim().parse::<u64>().ok())
+            .unwrap_or(0)
+    }
+
+    // Authority set for the chain as of `block_id`, read from
+    // sawtooth.consensus.authorities as a comma-separated list of hex
+    // public keys.
+    // Entries are lowercased to match encode_hex, which always produces
+    // lowercase hex -- otherwise an authority entered in mixed case in the
+    // setting would never match a signer's encoded public key.
+    fn get_authorities(&mut self, block_id: BlockId) -> Vec<String> {
+        self.service
+            .get_settings(block_id, vec![String::from(SETTING_AUTHORITIES)])
+            .ok()
+            .and_then(|settings| settings.get(SETTING_AUTHORITIES).cloned())
+            .map(|raw| {
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_lowercase)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Fork-choice rule for the chain as of `settings_block_id`, read from
+    // sawtooth.consensus.fork_choice. Defaults to the original greatest-id
+    // comparison when unset or unrecognized.
+    fn fork_choice_rule(&mut self, settings_block_id: BlockId) -> Box<dyn ForkChoice> {
+        let configured = self
+            .service
+            .get_settings(settings_block_id, vec![String::from(SETTING_FORK_CHOICE)])
+            .ok()
+            .and_then(|settings| settings.get(SETTING_FORK_CHOICE).cloned());
+
+        match configured.as_ref().map(String::as_str) {
+            Some(FORK_CHOICE_TIMESTAMP) => Box::new(TimestampForkChoice),
+            _ => Box::new(GreatestIdForkChoice),
+        }
+    }
+
+    fn verify_seal(&self, seal: &Seal, summary: &[u8]) -> bool {
+        let authority = match &self.authority {
+            Some(authority) => authority,
+            None => return false,
+        };
+
+        let public_key = match Secp256k1PublicKey::from_hex(&encode_hex(&seal.signer_public_key)) {
+            Ok(public_key) => public_key,
+            Err(_) => return false,
+        };
+
+        // Legacy seals (no timestamp) were signed over the bare summary;
+        // only seals written after the timestamp field existed cover it.
+        let signed = match seal.timestamp {
+            Some(timestamp) => signed_bytes(timestamp, summary),
+            None => summary.to_vec(),
+        };
+
+        authority
+            .context
+            .verify(&encode_hex(&seal.signature), &signed, &public_key)
+            .unwrap_or(false)
+    }
+
+    fn record_peer_connected(&mut self, peer_id: PeerId) {
+        self.peers.insert(peer_id);
+    }
+
+    fn record_peer_disconnected(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+
+    // Validator-set size used to compute vote quorums. Read from settings so
+    // it can be configured explicitly; otherwise falls back to the number of
+    // currently connected peers, plus itself.
+    fn validator_set_size(&mut self, settings_block_id: BlockId) -> usize {
+        let configured = self
+            .service
+            .get_settings(
+                settings_block_id,
+                vec![String::from(SETTING_VALIDATOR_SET_SIZE)],
+            )
+            .ok()
+            .and_then(|settings| settings.get(SETTING_VALIDATOR_SET_SIZE).cloned())
+            .and_then(|raw| raw.parse::<usize>().ok());
+
+        configured.unwrap_or_else(|| self.peers.len() + 1)
+    }
+
+    fn quorum(&mut self, settings_block_id: BlockId) -> usize {
+        let validator_set_size = self.validator_set_size(settings_block_id);
+        (validator_set_size * 2) / 3 + 1
+    }
+
+    fn broadcast_vote(&mut self, message_type: &str, block_id: BlockId) {
+        debug!("Broadcasting {} for {}", message_type, to_hex(&block_id));
+        self.service
+            .broadcast(message_type, block_id)
+            .expect("Failed to broadcast vote");
+    }
+
+    // Begin a prevote/precommit round for a BlockValid candidate instead of
+    // committing it outright. Stale candidates at or below the highest
+    // height we've already committed are dropped rather than voted on. If
+    // a round is already in flight for a different block, that candidate
+    // is superseded: it's explicitly ignored rather than left to dangle,
+    // since it's no longer tracked by `voting_round` and would otherwise
+    // never be committed, ignored, or timed out.
+    fn start_voting_round(&mut self, block: Block) {
+        if block.block_num <= self.highest_committed_height {
+            debug!("Dropping stale voting round for {}", DisplayBlock(&block));
+            self.pending_prevotes.remove(&block.block_id);
+            self.pending_precommits.remove(&block.block_id);
+            self.ignore_block(block.block_id);
+            return;
+        }
+
+        let block_id = block.block_id.clone();
+        let block_num = block.block_num;
+        let settings_block_id = block.previous_id.clone();
+
+        if let Some(previous_round) = self.voting_round.take() {
+            if previous_round.block_id == block_id {
+                // Already running this exact round -- e.g. a duplicate
+                // BlockValid notification. Put it back unchanged rather
+                // than resetting its timeout clock and re-prevoting.
+                self.voting_round = Some(previous_round);
+                return;
+            }
+
+            debug!(
+                "Superseding voting round for {} with {}",
+                to_hex(&previous_round.block_id),
+                to_hex(&block_id)
+            );
+
+            // The superseded candidate's votes are now dead weight: drop
+            // them alongside it instead of leaving them in prevotes/
+            // precommits until (if ever) this height happens to finalize.
+            if let Some(by_block) = self.prevotes.get_mut(&previous_round.block_num) {
+                by_block.remove(&previous_round.block_id);
+            }
+            if let Some(by_block) = self.precommits.get_mut(&previous_round.block_num) {
+                by_block.remove(&previous_round.block_id);
+            }
+            self.pending_prevotes.remove(&previous_round.block_id);
+            self.pending_precommits.remove(&previous_round.block_id);
+
+            self.ignore_block(previous_round.block_id);
+        }
+
+        self.voting_round = Some(VotingRound {
+            block_id: block_id.clone(),
+            block_num,
+            started_at: time::Instant::now(),
+        });
+
+        // Replay any votes that arrived for this block_id before this
+        // node knew its height, now that the active round makes that
+        // knowable via vote_block_num.
+        if let Some(voters) = self.pending_prevotes.remove(&block_id) {
+            for voter in voters {
+                let settings_block_id = settings_block_id.clone();
+                self.record_prevote(voter, block_id.clone(), block_num, settings_block_id);
+            }
+        }
+        if let Some(voters) = self.pending_precommits.remove(&block_id) {
+            for voter in voters {
+                let settings_block_id = settings_block_id.clone();
+                self.record_precommit(voter, block_id.clone(), block_num, settings_block_id);
+            }
+        }
+
+        // Replaying buffered precommits above may have already reached
+        // quorum and committed this block_id, clearing voting_round; don't
+        // go on to prevote/broadcast for a round that's already finished.
+        let still_active = self
+            .voting_round
+            .as_ref()
+            .map_or(false, |round| round.block_id == block_id);
+        if !still_active {
+            return;
+        }
+
+        let local_peer_id = self.local_peer_id.clone();
+        self.record_prevote(local_peer_id, block_id.clone(), block_num, settings_block_id);
+        self.broadcast_vote(PREVOTE_MESSAGE, block_id);
+    }
+
+    // Buffers a prevote/precommit whose block_id this node hasn't synced
+    // yet, so `vote_block_num` couldn't resolve its height. Replayed by
+    // `start_voting_round` once this node's own view of the block catches
+    // up and that block_id becomes the active round.
+    fn buffer_pending_prevote(&mut self, voter: PeerId, block_id: BlockId) {
+        if !self.pending_prevotes.contains_key(&block_id)
+            && self.pending_prevotes.len() >= MAX_PENDING_VOTE_BLOCKS
+        {
+            debug!(
+                "Dropping prevote for not-yet-synced {}: pending vote buffer full",
+                to_hex(&block_id)
+            );
+            return;
+        }
+
+        self.pending_prevotes
+            .entry(block_id)
+            .or_insert_with(HashSet::new)
+            .insert(voter);
+    }
+
+    fn buffer_pending_precommit(&mut self, voter: PeerId, block_id: BlockId) {
+        if !self.pending_precommits.contains_key(&block_id)
+            && self.pending_precommits.len() >= MAX_PENDING_VOTE_BLOCKS
+        {
+            debug!(
+                "Dropping precommit for not-yet-synced {}: pending vote buffer full",
+                to_hex(&block_id)
+            );
+            return;
+        }
+
+        self.pending_precommits
+            .entry(block_id)
+            .or_insert_with(HashSet::new)
+            .insert(voter);
+    }
+
+    // Records a prevote for `block_id` at `block_num`, dropping it instead
+    // if `block_num` is at or below the highest height already committed
+    // (a stale vote for an abandoned or finalized round). A precommit is
+    // only ever broadcast once quorum is reached AND `voting_round` still
+    // points at this exact block_id, so late prevotes for a block this
+    // node has since moved on from can't make it precommit two different
+    // blocks at the same height.
+    fn record_prevote(
+        &mut self,
+        voter: PeerId,
+        block_id: BlockId,
+        block_num: u64,
+        settings_block_id: BlockId,
+    ) {
+        if block_num <= self.highest_committed_height {
+            debug!("Dropping stale prevote for {}", to_hex(&block_id));
+            return;
+        }
+
+        self.prevotes
+            .entry(block_num)
+            .or_insert_with(HashMap::new)
+            .entry(block_id.clone())
+            .or_insert_with(HashSet::new)
+            .insert(voter);
+
+        let vote_count = self
+            .prevotes
+            .get(&block_num)
+            .and_then(|by_block| by_block.get(&block_id))
+            .map_or(0, HashSet::len);
+        let quorum = self.quorum(settings_block_id.clone());
+
+        let round_matches = self
+            .voting_round
+            .as_ref()
+            .map_or(false, |round| round.block_id == block_id);
+
+        let already_precommitted = self
+            .precommits
+            .get(&block_num)
+            .map_or(false, |by_block| by_block.contains_key(&block_id));
+
+        if vote_count >= quorum && round_matches && !already_precommitted {
+            info!(
+                "Prevote quorum reached ({}/{}) for {}; precommitting",
+                vote_count,
+                quorum,
+                to_hex(&block_id)
+            );
+
+            let local_peer_id = self.local_peer_id.clone();
+            self.record_precommit(
+                local_peer_id,
+                block_id.clone(),
+                block_num,
+                settings_block_id,
+            );
+            self.broadcast_vote(PRECOMMIT_MESSAGE, block_id);
+        }
+    }
+
+    // Records a precommit for `block_id` at `block_num`, with the same
+    // staleness drop as `record_prevote`. Commits only trigger when
+    // `voting_round` still points at `block_id`, which is what keeps this
+    // node from precommitting two different blocks at the same height.
+    fn record_precommit(
+        &mut self,
+        voter: PeerId,
+        block_id: BlockId,
+        block_num: u64,
+        settings_block_id: BlockId,
+    ) {
+        if block_num <= self.highest_committed_height {
+            debug!("Dropping stale precommit for {}", to_hex(&block_id));
+            return;
+        }
+
+        self.precommits
+            .entry(block_num)
+            .or_insert_with(HashMap::new)
+            .entry(block_id.clone())
+            .or_insert_with(HashSet::new)
+            .insert(voter);
+
+        let vote_count = self
+            .precommits
+            .get(&block_num)
+            .and_then(|by_block| by_block.get(&block_id))
+            .map_or(0, HashSet::len);
+        let quorum = self.quorum(settings_block_id);
+
+        let round_matches = self
+            .voting_round
+            .as_ref()
+            .map_or(false, |round| round.block_id == block_id);
+
+        if vote_count >= quorum && round_matches {
+            info!(
+                "Precommit quorum reached ({}/{}) for {}",
+                vote_count,
+                quorum,
+                to_hex(&block_id)
+            );
+
+            self.voting_round = None;
+            self.highest_committed_height = block_num;
+            self.commit_block(block_id);
+
+            // Votes at or below the height that just finalized are no
+            // longer useful to anyone; drop them so prevotes/precommits
+            // don't grow without bound over the life of a running
+            // validator.
+            let finalized_height = self.highest_committed_height;
+            self.prevotes.retain(|height, _| *height > finalized_height);
+            self.precommits.retain(|height, _| *height > finalized_height);
+
+            // Anything still buffered was for a block_id that never
+            // became this node's active round; with a new height
+            // finalized, whatever candidates come next will be gossiped
+            // fresh rather than replayed from here.
+            self.pending_prevotes.clear();
+            self.pending_precommits.clear();
+        }
+    }
+
+    // Abandons the in-flight voting round if it's been open longer than
+    // `timeout`, returning the block_id to ignore.
+    fn expire_voting_round(&mut self, timeout: time::Duration) -> Option<BlockId> {
+        let expired = self
+            .voting_round
+            .as_ref()
+            .map_or(false, |round| round.started_at.elapsed() > timeout);
+
+        if !expired {
+            return None;
+        }
+
+        let round = self.voting_round.take()?;
+
+        // Same cleanup as superseding a round in start_voting_round: an
+        // abandoned candidate's votes are dead weight, so drop them here
+        // too instead of only when some later round happens to finalize.
+        if let Some(by_block) = self.prevotes.get_mut(&round.block_num) {
+            by_block.remove(&round.block_id);
+        }
+        if let Some(by_block) = self.precommits.get_mut(&round.block_num) {
+            by_block.remove(&round.block_id);
+        }
+        self.pending_prevotes.remove(&round.block_id);
+        self.pending_precommits.remove(&round.block_id);
+
+        Some(round.block_id)
+    }
 }
 
 pub struct DevmodeEngine {}
@@ -229,10 +1156,12 @@ impl Engine for DevmodeEngine {
         startup_state: StartupState,
     ) -> Result<(), Error> {
         let mut service = DevmodeService::new(service);
+        service.set_local_peer_id(startup_state.local_peer_info.peer_id);
         let mut chain_head = startup_state.chain_head;
 
         let mut wait_time = service.calculate_wait_time(chain_head.block_id.clone());
         let mut published_at_height = false;
+        let mut publish_attempted = false;
         let mut start = time::Instant::now();
 
         service.initialize_block();
@@ -260,7 +1189,7 @@ impl Engine for DevmodeEngine {
                                 continue;
                             }
 
-                            if check_consensus(&block) {
+                            if service.check_consensus(&block) {
                                 info!("Passed consensus check: {}", DisplayBlock(&block));
                                 service.check_block(block.block_id);
                             } else {
@@ -282,31 +1211,29 @@ impl Engine for DevmodeEngine {
                                 DisplayBlock(&block)
                             );
 
-                            // Advance the chain if possible.
-                            if block.block_num > chain_head.block_num
-                                || (block.block_num == chain_head.block_num
-                                    && block.block_id > chain_head.block_id)
-                            {
-                                info!("Committing {}", DisplayBlock(&block));
-                                service.commit_block(block_id);
-                            } else if block.block_num < chain_head.block_num {
-                                let mut chain_block = chain_head;
-                                loop {
-                                    chain_block = service.get_block(&chain_block.previous_id);
-                                    if chain_block.block_num == block.block_num {
-                                        break;
-                                    }
+                            // Advance the chain if the configured fork-choice
+                            // rule prefers the candidate, by putting it up
+                            // for a prevote/precommit round rather than
+                            // committing it outright.
+                            let fork_choice =
+                                service.fork_choice_rule(chain_head.block_id.clone());
+
+                            match fork_choice.choose(&chain_head, &block, &mut service) {
+                                Decision::Commit => {
+                                    info!("Entering voting round for {}", DisplayBlock(&block));
+                                    service.start_voting_round(block);
+                                }
+                                Decision::Fork => {
+                                    info!(
+                                        "Entering voting round for fork {}",
+                                        DisplayBlock(&block)
+                                    );
+                                    service.start_voting_round(block);
                                 }
-                                if block.block_id > chain_block.block_id {
-                                    info!("Switching to new fork {}", DisplayBlock(&block));
-                                    service.commit_block(block_id);
-                                } else {
-                                    info!("Ignoring fork {}", DisplayBlock(&block));
+                                Decision::Ignore => {
+                                    info!("Ignoring {}", DisplayBlock(&block));
                                     service.ignore_block(block_id);
                                 }
-                            } else {
-                                info!("Ignoring {}", DisplayBlock(&block));
-                                service.ignore_block(block_id);
                             }
                         }
 
@@ -319,9 +1246,11 @@ impl Engine for DevmodeEngine {
                             );
 
                             service.cancel_block();
+                            service.reset_publish_state();
 
                             wait_time = service.calculate_wait_time(new_chain_head.clone());
                             published_at_height = false;
+                            publish_attempted = false;
                             start = time::Instant::now();
 
                             service.initialize_block();
@@ -355,11 +1284,67 @@ impl Engine for DevmodeEngine {
                                         to_hex(&message.content)
                                     );
                                 }
+
+                                DevmodeMessage::Prevote => {
+                                    info!(
+                                        "Received prevote from {} for {}",
+                                        to_hex(&sender_id),
+                                        to_hex(&message.content)
+                                    );
+                                    let block_num = service.vote_block_num(&message.content);
+                                    if let Some(block_num) = block_num {
+                                        service.record_prevote(
+                                            sender_id,
+                                            message.content,
+                                            block_num,
+                                            chain_head.block_id.clone(),
+                                        );
+                                    } else {
+                                        debug!(
+                                            "Buffering prevote for not-yet-synced block {}",
+                                            to_hex(&message.content)
+                                        );
+                                        service.buffer_pending_prevote(sender_id, message.content);
+                                    }
+                                }
+
+                                DevmodeMessage::Precommit => {
+                                    info!(
+                                        "Received precommit from {} for {}",
+                                        to_hex(&sender_id),
+                                        to_hex(&message.content)
+                                    );
+                                    let block_num = service.vote_block_num(&message.content);
+                                    if let Some(block_num) = block_num {
+                                        service.record_precommit(
+                                            sender_id,
+                                            message.content,
+                                            block_num,
+                                            chain_head.block_id.clone(),
+                                        );
+                                    } else {
+                                        debug!(
+                                            "Buffering precommit for not-yet-synced block {}",
+                                            to_hex(&message.content)
+                                        );
+                                        service
+                                            .buffer_pending_precommit(sender_id, message.content);
+                                    }
+                                }
                             }
                         }
 
-                        // Devmode doesn't care about peer notifications
-                        // or invalid blocks.
+                        Update::PeerConnected(peer_info) => {
+                            info!("Peer connected: {}", to_hex(&peer_info.peer_id));
+                            service.record_peer_connected(peer_info.peer_id);
+                        }
+
+                        Update::PeerDisconnected(peer_id) => {
+                            info!("Peer disconnected: {}", to_hex(&peer_id));
+                            service.record_peer_disconnected(&peer_id);
+                        }
+
+                        // Devmode doesn't care about invalid blocks.
                         _ => {}
                     }
                 }
@@ -372,12 +1357,31 @@ impl Engine for DevmodeEngine {
                 Err(RecvTimeoutError::Timeout) => {}
             }
 
-            if !published_at_height && time::Instant::now().duration_since(start) > wait_time {
-                info!("Timer expired -- publishing block");
-                let new_block_id = service.finalize_block();
-                published_at_height = true;
+            if let Some(block_id) = service.expire_voting_round(VOTING_ROUND_TIMEOUT) {
+                info!("Voting round timed out for {}", to_hex(&block_id));
+                service.ignore_block(block_id);
+            }
+
+            if !published_at_height {
+                let retry_due = service
+                    .poll_expired_retries()
+                    .into_iter()
+                    .any(|action| action == PendingAction::PublishRetry);
+
+                let timer_due =
+                    !publish_attempted && time::Instant::now().duration_since(start) > wait_time;
 
-                service.broadcast_published_block(new_block_id);
+                if timer_due {
+                    info!("Timer expired -- publishing block");
+                    publish_attempted = true;
+                }
+
+                if retry_due || timer_due {
+                    if let Some(new_block_id) = service.try_publish() {
+                        published_at_height = true;
+                        service.broadcast_published_block(new_block_id);
+                    }
+                }
             }
         }
 
@@ -430,20 +1434,12 @@ fn message_type(update: &Update) -> &str {
     }
 }
 
-fn check_consensus(block: &Block) -> bool {
-    block.payload == create_consensus(&block.summary)
-}
-
-fn create_consensus(summary: &[u8]) -> Vec<u8> {
-    let mut consensus: Vec<u8> = Vec::from(&b"Devmode"[..]);
-    consensus.extend_from_slice(summary);
-    consensus
-}
-
 pub enum DevmodeMessage {
     Ack,
     Published,
     Received,
+    Prevote,
+    Precommit,
 }
 
 impl FromStr for DevmodeMessage {
@@ -454,7 +1450,619 @@ impl FromStr for DevmodeMessage {
             "ack" => Ok(DevmodeMessage::Ack),
             "published" => Ok(DevmodeMessage::Published),
             "received" => Ok(DevmodeMessage::Received),
+            PREVOTE_MESSAGE => Ok(DevmodeMessage::Prevote),
+            PRECOMMIT_MESSAGE => Ok(DevmodeMessage::Precommit),
             _ => Err("Invalid message type"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A minimal in-memory Service stand-in so DevmodeService's own logic can
+    // be exercised without a running validator. Only returns what the
+    // tests below actually ask for.
+    #[derive(Default)]
+    struct MockService {
+        settings: HashMap<BlockId, HashMap<String, String>>,
+        blocks: HashMap<BlockId, Block>,
+    }
+
+    impl Service for MockService {
+        fn send_to(
+            &mut self,
+            _peer: &PeerId,
+            _message_type: &str,
+            _payload: Vec<u8>,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn broadcast(&mut self, _message_type: &str, _payload: Vec<u8>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn initialize_block(&mut self, _previous_id: Option<BlockId>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
+            Ok(vec![])
+        }
+
+        fn finalize_block(&mut self, _data: Vec<u8>) -> Result<BlockId, Error> {
+            Ok(vec![])
+        }
+
+        fn cancel_block(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn check_blocks(&mut self, _priority: Vec<BlockId>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn commit_block(&mut self, _block_id: BlockId) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn ignore_block(&mut self, _block_id: BlockId) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn fail_block(&mut self, _block_id: BlockId) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn get_blocks(
+            &mut self,
+            block_ids: Vec<BlockId>,
+        ) -> Result<HashMap<BlockId, Block>, Error> {
+            Ok(block_ids
+                .into_iter()
+                .filter_map(|id| self.blocks.get(&id).cloned().map(|block| (id, block)))
+                .collect())
+        }
+
+        fn get_chain_head(&mut self) -> Result<Block, Error> {
+            Err(Error::UnknownBlock("no chain head in MockService".into()))
+        }
+
+        fn get_settings(
+            &mut self,
+            block_id: BlockId,
+            settings: Vec<String>,
+        ) -> Result<HashMap<String, String>, Error> {
+            let configured = self.settings.get(&block_id).cloned().unwrap_or_default();
+            Ok(settings
+                .into_iter()
+                .filter_map(|key| configured.get(&key).cloned().map(|value| (key, value)))
+                .collect())
+        }
+
+        fn get_state(
+            &mut self,
+            _block_id: BlockId,
+            _addresses: Vec<String>,
+        ) -> Result<HashMap<String, Vec<u8>>, Error> {
+            Ok(HashMap::new())
+        }
+    }
+
+    fn mock_devmode_service(mock: MockService) -> DevmodeService {
+        DevmodeService::new(Box::new(mock))
+    }
+
+    #[test]
+    fn seal_legacy_round_trips_through_bytes() {
+        let seal = Seal {
+            timestamp: None,
+            signer_public_key: vec![2; PUBLIC_KEY_LEN],
+            signature: vec![3; SIGNATURE_LEN],
+        };
+
+        let parsed = Seal::parse(&seal.as_bytes()).expect("legacy seal should parse");
+
+        assert_eq!(parsed.timestamp, None);
+        assert_eq!(parsed.signer_public_key, seal.signer_public_key);
+        assert_eq!(parsed.signature, seal.signature);
+    }
+
+    #[test]
+    fn seal_timestamped_round_trips_through_bytes() {
+        let seal = Seal {
+            timestamp: Some(1_700_000_000),
+            signer_public_key: vec![4; PUBLIC_KEY_LEN],
+            signature: vec![5; SIGNATURE_LEN],
+        };
+
+        let parsed = Seal::parse(&seal.as_bytes()).expect("timestamped seal should parse");
+
+        assert_eq!(parsed.timestamp, seal.timestamp);
+        assert_eq!(parsed.signer_public_key, seal.signer_public_key);
+        assert_eq!(parsed.signature, seal.signature);
+    }
+
+    #[test]
+    fn seal_parse_accepts_legacy_seals_written_before_timestamps_existed() {
+        // Matches the exact shape chunk0-1 authority mode produced: tag,
+        // public key, signature -- no version byte, no timestamp.
+        let mut consensus = Vec::from(CONSENSUS_TAG);
+        consensus.extend(vec![6; PUBLIC_KEY_LEN]);
+        consensus.extend(vec![7; SIGNATURE_LEN]);
+
+        let seal = Seal::parse(&consensus).expect("pre-existing legacy seal must still parse");
+
+        assert_eq!(seal.timestamp, None);
+    }
+
+    #[test]
+    fn seal_parse_rejects_wrong_tag() {
+        let mut consensus = b"NotDevmode".to_vec();
+        consensus.extend(vec![8; PUBLIC_KEY_LEN + SIGNATURE_LEN]);
+
+        assert!(Seal::parse(&consensus).is_none());
+    }
+
+    #[test]
+    fn seal_parse_rejects_truncated_input() {
+        let mut consensus = Vec::from(CONSENSUS_TAG);
+        consensus.push(SEAL_VERSION_TIMESTAMPED);
+        consensus.extend(vec![9; PUBLIC_KEY_LEN]);
+
+        assert!(Seal::parse(&consensus).is_none());
+    }
+
+    #[test]
+    fn validator_set_size_uses_configured_setting() {
+        let mut settings = HashMap::new();
+        settings.insert(
+            vec![1],
+            vec![(SETTING_VALIDATOR_SET_SIZE.to_string(), "7".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        let mut service = mock_devmode_service(MockService {
+            settings,
+            ..Default::default()
+        });
+
+        assert_eq!(service.validator_set_size(vec![1]), 7);
+    }
+
+    #[test]
+    fn validator_set_size_falls_back_to_peer_count_plus_one_when_unconfigured() {
+        let mut service = mock_devmode_service(MockService::default());
+        service.peers.insert(vec![1]);
+        service.peers.insert(vec![2]);
+
+        assert_eq!(service.validator_set_size(vec![0]), 3);
+    }
+
+    #[test]
+    fn validator_set_size_falls_back_when_setting_is_not_a_number() {
+        let mut settings = HashMap::new();
+        settings.insert(
+            vec![1],
+            vec![(SETTING_VALIDATOR_SET_SIZE.to_string(), "not-a-number".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        let mut service = mock_devmode_service(MockService {
+            settings,
+            ..Default::default()
+        });
+
+        assert_eq!(service.validator_set_size(vec![1]), 1);
+    }
+
+    #[test]
+    fn quorum_is_two_thirds_plus_one_of_validator_set_size() {
+        let mut settings = HashMap::new();
+        settings.insert(
+            vec![1],
+            vec![(SETTING_VALIDATOR_SET_SIZE.to_string(), "4".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        let mut service = mock_devmode_service(MockService {
+            settings,
+            ..Default::default()
+        });
+
+        // (4 * 2) / 3 + 1 == 3: tolerates one fault out of four validators.
+        assert_eq!(service.quorum(vec![1]), 3);
+    }
+
+    #[test]
+    fn poll_expired_returns_only_keys_past_their_deadline() {
+        let mut delay = HashMapDelay::new();
+        delay.insert("due", time::Duration::from_secs(0));
+        delay.insert("not-due", time::Duration::from_secs(60));
+
+        let expired = delay.poll_expired();
+
+        assert_eq!(expired, vec!["due"]);
+    }
+
+    #[test]
+    fn poll_expired_removes_returned_keys_so_they_fire_once() {
+        let mut delay = HashMapDelay::new();
+        delay.insert("due", time::Duration::from_secs(0));
+
+        assert_eq!(delay.poll_expired(), vec!["due"]);
+        assert!(delay.poll_expired().is_empty());
+    }
+
+    #[test]
+    fn remove_cancels_a_pending_deadline() {
+        let mut delay = HashMapDelay::new();
+        delay.insert("due", time::Duration::from_secs(0));
+        delay.remove(&"due");
+
+        assert!(delay.poll_expired().is_empty());
+    }
+
+    fn test_block(block_id: u8, block_num: u64, previous_id: u8, payload: Vec<u8>) -> Block {
+        Block {
+            block_id: vec![block_id],
+            previous_id: vec![previous_id],
+            signer_id: vec![],
+            block_num,
+            payload,
+            summary: vec![],
+        }
+    }
+
+    fn seal_payload(timestamp: Option<u64>) -> Vec<u8> {
+        Seal {
+            timestamp,
+            signer_public_key: vec![0; PUBLIC_KEY_LEN],
+            signature: vec![0; SIGNATURE_LEN],
+        }
+        .as_bytes()
+    }
+
+    #[test]
+    fn greatest_id_fork_choice_commits_a_taller_candidate() {
+        let current_head = test_block(1, 1, 0, vec![]);
+        let candidate = test_block(2, 2, 1, vec![]);
+        let mut service = mock_devmode_service(MockService::default());
+
+        assert!(matches!(
+            GreatestIdForkChoice.choose(&current_head, &candidate, &mut service),
+            Decision::Commit
+        ));
+    }
+
+    #[test]
+    fn greatest_id_fork_choice_breaks_equal_height_ties_on_block_id() {
+        let current_head = test_block(5, 1, 0, vec![]);
+        let larger_id = test_block(9, 1, 0, vec![]);
+        let smaller_id = test_block(1, 1, 0, vec![]);
+        let mut service = mock_devmode_service(MockService::default());
+
+        assert!(matches!(
+            GreatestIdForkChoice.choose(&current_head, &larger_id, &mut service),
+            Decision::Commit
+        ));
+        assert!(matches!(
+            GreatestIdForkChoice.choose(&current_head, &smaller_id, &mut service),
+            Decision::Ignore
+        ));
+    }
+
+    #[test]
+    fn greatest_id_fork_choice_forks_a_shorter_chain_with_a_larger_block_id() {
+        // Chain: block 2 (id=2) -> block 1 (id=1), current head at height 2.
+        let chain_block_1 = test_block(1, 1, 0, vec![]);
+        let current_head = test_block(2, 2, 1, vec![]);
+        let mut blocks = HashMap::new();
+        blocks.insert(vec![1], chain_block_1);
+        let mut service = mock_devmode_service(MockService {
+            blocks,
+            ..Default::default()
+        });
+
+        let reorg_candidate = test_block(9, 1, 0, vec![]);
+        assert!(matches!(
+            GreatestIdForkChoice.choose(&current_head, &reorg_candidate, &mut service),
+            Decision::Fork
+        ));
+
+        let weaker_candidate = test_block(0, 1, 0, vec![]);
+        assert!(matches!(
+            GreatestIdForkChoice.choose(&current_head, &weaker_candidate, &mut service),
+            Decision::Ignore
+        ));
+    }
+
+    #[test]
+    fn timestamp_fork_choice_commits_a_taller_candidate() {
+        let current_head = test_block(1, 1, 0, vec![]);
+        let candidate = test_block(2, 2, 1, vec![]);
+        let mut service = mock_devmode_service(MockService::default());
+
+        assert!(matches!(
+            TimestampForkChoice.choose(&current_head, &candidate, &mut service),
+            Decision::Commit
+        ));
+    }
+
+    #[test]
+    fn timestamp_fork_choice_prefers_the_earlier_timestamp_at_equal_height() {
+        let current_head = test_block(1, 1, 0, seal_payload(Some(200)));
+        let earlier_candidate = test_block(2, 1, 0, seal_payload(Some(100)));
+        let later_candidate = test_block(3, 1, 0, seal_payload(Some(300)));
+        let mut service = mock_devmode_service(MockService::default());
+
+        assert!(matches!(
+            TimestampForkChoice.choose(&current_head, &earlier_candidate, &mut service),
+            Decision::Commit
+        ));
+        assert!(matches!(
+            TimestampForkChoice.choose(&current_head, &later_candidate, &mut service),
+            Decision::Ignore
+        ));
+    }
+
+    #[test]
+    fn timestamp_fork_choice_falls_back_to_block_id_without_seal_timestamps() {
+        let current_head = test_block(5, 1, 0, vec![]);
+        let larger_id = test_block(9, 1, 0, vec![]);
+        let mut service = mock_devmode_service(MockService::default());
+
+        assert!(matches!(
+            TimestampForkChoice.choose(&current_head, &larger_id, &mut service),
+            Decision::Commit
+        ));
+    }
+
+    // validator_set_size falls back to peers.len() + 1, so one configured
+    // peer gives a set size of 2 and a quorum of 2 -- just enough to
+    // exercise "second vote reaches quorum" without a third vote.
+    fn service_with_one_peer() -> DevmodeService {
+        let mut service = mock_devmode_service(MockService::default());
+        service.peers.insert(vec![99]);
+        service
+    }
+
+    #[test]
+    fn second_prevote_reaching_quorum_triggers_a_local_precommit() {
+        let mut service = service_with_one_peer();
+        let block = test_block(1, 1, 0, vec![]);
+        service.start_voting_round(block.clone());
+
+        // start_voting_round already recorded the local peer's own
+        // prevote; a second, distinct voter should reach quorum (2).
+        service.record_prevote(vec![99], block.block_id.clone(), block.block_num, vec![0]);
+
+        let local_precommitted = service
+            .precommits
+            .get(&block.block_num)
+            .and_then(|by_block| by_block.get(&block.block_id))
+            .map_or(false, |voters| voters.contains(&service.local_peer_id));
+        assert!(local_precommitted, "prevote quorum should trigger a local precommit");
+        assert_eq!(service.highest_committed_height, 0, "not yet precommit quorum");
+    }
+
+    #[test]
+    fn precommit_quorum_commits_the_block_and_advances_highest_committed_height() {
+        let mut service = service_with_one_peer();
+        let block = test_block(1, 1, 0, vec![]);
+        service.start_voting_round(block.clone());
+        service.record_prevote(vec![99], block.block_id.clone(), block.block_num, vec![0]);
+
+        // Prevote quorum above already cast the local precommit; the
+        // peer's precommit should now reach precommit quorum (2) too.
+        service.record_precommit(vec![99], block.block_id.clone(), block.block_num, vec![0]);
+
+        assert_eq!(service.highest_committed_height, block.block_num);
+        assert!(service.voting_round.is_none());
+    }
+
+    #[test]
+    fn stale_vote_at_or_below_highest_committed_height_is_dropped() {
+        let mut service = service_with_one_peer();
+        service.highest_committed_height = 5;
+
+        service.record_prevote(vec![99], vec![1], 5, vec![0]);
+
+        assert!(service.prevotes.get(&5).is_none(), "stale vote must not be recorded");
+    }
+
+    #[test]
+    fn superseding_a_round_evicts_its_votes_from_prevotes_and_precommits() {
+        let mut service = service_with_one_peer();
+        let block_a = test_block(1, 1, 0, vec![]);
+        service.start_voting_round(block_a.clone());
+        service.record_precommit(vec![99], block_a.block_id.clone(), block_a.block_num, vec![0]);
+
+        let block_b = test_block(2, 1, 0, vec![]);
+        service.start_voting_round(block_b);
+
+        assert!(
+            service
+                .prevotes
+                .get(&block_a.block_num)
+                .map_or(true, |by_block| !by_block.contains_key(&block_a.block_id)),
+            "superseded round's prevotes must be evicted"
+        );
+        assert!(
+            service
+                .precommits
+                .get(&block_a.block_num)
+                .map_or(true, |by_block| !by_block.contains_key(&block_a.block_id)),
+            "superseded round's precommits must be evicted"
+        );
+    }
+
+    #[test]
+    fn a_buffered_pending_vote_is_replayed_once_the_block_becomes_the_active_round() {
+        let mut service = service_with_one_peer();
+        let block = test_block(1, 1, 0, vec![]);
+
+        // The peer's prevote arrives before this node has synced the
+        // block, so it can't be recorded directly and is buffered.
+        service.buffer_pending_prevote(vec![99], block.block_id.clone());
+        assert!(service.pending_prevotes.contains_key(&block.block_id));
+
+        service.start_voting_round(block.clone());
+
+        assert!(
+            !service.pending_prevotes.contains_key(&block.block_id),
+            "replayed vote must be drained from the pending buffer"
+        );
+        let replayed = service
+            .prevotes
+            .get(&block.block_num)
+            .and_then(|by_block| by_block.get(&block.block_id))
+            .map_or(false, |voters| voters.contains(&vec![99]));
+        assert!(replayed, "buffered prevote must be recorded once the round is active");
+    }
+
+    // Distinct 32-byte test keys, hex-encoded for Secp256k1PrivateKey::from_hex.
+    const AUTHORITY_KEY_HEX: &str =
+        "0101010101010101010101010101010101010101010101010101010101010101";
+    const OTHER_KEY_HEX: &str =
+        "0202020202020202020202020202020202020202020202020202020202020202";
+
+    fn test_authority(key_hex: &str) -> AuthorityConfig {
+        let context = create_context("secp256k1").expect("failed to create secp256k1 context");
+        let private_key =
+            Secp256k1PrivateKey::from_hex(key_hex).expect("invalid test private key");
+        let public_key = context
+            .get_public_key(private_key.as_ref())
+            .expect("failed to derive test public key");
+
+        AuthorityConfig {
+            context,
+            signer: Box::new(private_key),
+            public_key,
+        }
+    }
+
+    // Signs `summary` the way create_consensus/verify_seal do for the given
+    // timestamp: domain-separated when Some, bare when None (matching a
+    // pre-chunk0-4 legacy seal).
+    fn sign_seal(authority: &AuthorityConfig, timestamp: Option<u64>, summary: &[u8]) -> Seal {
+        let message = match timestamp {
+            Some(ts) => signed_bytes(ts, summary),
+            None => summary.to_vec(),
+        };
+        let signature_hex = authority
+            .context
+            .sign(&message, authority.signer.as_ref())
+            .expect("failed to sign test seal");
+
+        Seal {
+            timestamp,
+            signer_public_key: authority.public_key.as_slice().to_vec(),
+            signature: decode_hex(&signature_hex),
+        }
+    }
+
+    // A DevmodeService running in authority mode (its own signing key is
+    // unrelated to -- and never asserted against -- the authorized_key_hex
+    // on the authorities list, since check_consensus only ever verifies a
+    // seal against the signer's own embedded public key).
+    fn authority_mode_service(
+        authorized_key_hex: &str,
+        authorities_block_id: BlockId,
+        extra_settings: Vec<(String, String)>,
+    ) -> DevmodeService {
+        let mut authorities_settings: HashMap<String, String> =
+            extra_settings.into_iter().collect();
+        authorities_settings.insert(
+            SETTING_AUTHORITIES.to_string(),
+            authorized_key_hex.to_string(),
+        );
+        let mut settings = HashMap::new();
+        settings.insert(authorities_block_id, authorities_settings);
+
+        let mut service = mock_devmode_service(MockService {
+            settings,
+            ..Default::default()
+        });
+        service.authority = Some(test_authority(AUTHORITY_KEY_HEX));
+        service
+    }
+
+    #[test]
+    fn check_consensus_accepts_a_block_signed_by_an_authorized_key() {
+        let signer = test_authority(AUTHORITY_KEY_HEX);
+        let authorized_key_hex = encode_hex(signer.public_key.as_slice());
+        let mut service = authority_mode_service(&authorized_key_hex, vec![0], vec![]);
+
+        let seal = sign_seal(&signer, Some(1_000), &[]);
+        let block = test_block(1, 1, 0, seal.as_bytes());
+
+        assert!(service.check_consensus(&block));
+    }
+
+    #[test]
+    fn check_consensus_rejects_a_block_signed_by_an_unauthorized_key() {
+        let signer = test_authority(AUTHORITY_KEY_HEX);
+        let other_key_hex = encode_hex(test_authority(OTHER_KEY_HEX).public_key.as_slice());
+        // Only other_key_hex is on the authority list -- not the signer's.
+        let mut service = authority_mode_service(&other_key_hex, vec![0], vec![]);
+
+        let seal = sign_seal(&signer, Some(1_000), &[]);
+        let block = test_block(1, 1, 0, seal.as_bytes());
+
+        assert!(!service.check_consensus(&block));
+    }
+
+    #[test]
+    fn check_consensus_rejects_a_corrupted_signature() {
+        let signer = test_authority(AUTHORITY_KEY_HEX);
+        let authorized_key_hex = encode_hex(signer.public_key.as_slice());
+        let mut service = authority_mode_service(&authorized_key_hex, vec![0], vec![]);
+
+        let mut seal = sign_seal(&signer, Some(1_000), &[]);
+        seal.signature[0] ^= 0xff;
+        let block = test_block(1, 1, 0, seal.as_bytes());
+
+        assert!(!service.check_consensus(&block));
+    }
+
+    #[test]
+    fn check_consensus_rejects_a_legacy_seal_at_or_above_the_timestamped_cutover_height() {
+        let signer = test_authority(AUTHORITY_KEY_HEX);
+        let authorized_key_hex = encode_hex(signer.public_key.as_slice());
+        let mut service = authority_mode_service(
+            &authorized_key_hex,
+            vec![0],
+            vec![(
+                SETTING_SEAL_MIN_TIMESTAMPED_HEIGHT.to_string(),
+                "5".to_string(),
+            )],
+        );
+
+        let legacy_seal = sign_seal(&signer, None, &[]);
+        let block = test_block(1, 10, 0, legacy_seal.as_bytes());
+
+        assert!(!service.check_consensus(&block));
+    }
+
+    #[test]
+    fn check_consensus_accepts_a_legacy_seal_below_the_timestamped_cutover_height() {
+        let signer = test_authority(AUTHORITY_KEY_HEX);
+        let authorized_key_hex = encode_hex(signer.public_key.as_slice());
+        let mut service = authority_mode_service(
+            &authorized_key_hex,
+            vec![0],
+            vec![(
+                SETTING_SEAL_MIN_TIMESTAMPED_HEIGHT.to_string(),
+                "5".to_string(),
+            )],
+        );
+
+        let legacy_seal = sign_seal(&signer, None, &[]);
+        let block = test_block(1, 3, 0, legacy_seal.as_bytes());
+
+        assert!(service.check_consensus(&block));
+    }
+}